@@ -3,8 +3,25 @@ const PASS: Option<&str> = option_env!("MICRO_RDK_WIFI_PASSWORD");
 const ROBOT_ID: Option<&str> = option_env!("MICRO_RDK_ROBOT_ID");
 const ROBOT_SECRET: Option<&str> = option_env!("MICRO_RDK_ROBOT_SECRET");
 const ROBOT_APP_ADDRESS: Option<&str> = option_env!("MICRO_RDK_ROBOT_APP_ADDRESS");
+const MQTT_BROKER_URL: Option<&str> = option_env!("MICRO_RDK_MQTT_BROKER_URL");
+const MQTT_TOPIC_PREFIX: Option<&str> = option_env!("MICRO_RDK_MQTT_TOPIC_PREFIX");
+const OTA_HEALTH_TIMEOUT_SECS: Option<&str> = option_env!("MICRO_RDK_OTA_HEALTH_TIMEOUT_SECS");
+const OTA_MIN_HEAP_PCT: Option<&str> = option_env!("MICRO_RDK_OTA_MIN_HEAP_PCT");
+const WIFI_AUTH: Option<&str> = option_env!("MICRO_RDK_WIFI_AUTH");
+const WIFI_HIDDEN: Option<&str> = option_env!("MICRO_RDK_WIFI_HIDDEN");
 
-use std::{rc::Rc, time::Duration};
+mod ota;
+mod telemetry;
+mod wifi_provisioning;
+
+use std::{
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use async_io::Timer;
 use micro_rdk::{
@@ -42,6 +59,45 @@ macro_rules! generate_register_modules {
 
 include!(concat!(env!("OUT_DIR"), "/modules.rs"));
 
+/// Brings up the WiFi radio for the strongest known network actually in
+/// range. The network already stored as default is tried first since it's a
+/// single `Esp32WifiNetwork::new()` call away, but a bad guess there - the
+/// seeded network simply isn't in range right now, exactly the scenario
+/// multi-network roaming exists for - doesn't get to panic before the boot
+/// scan ever runs: a failed first attempt falls back to scanning for any
+/// other known network and retrying with whichever is actually present,
+/// only giving up once every known network has been tried.
+fn bring_up_wifi(storage: &NVSStorage, known: &[wifi_provisioning::KnownNetwork]) -> Esp32WifiNetwork {
+    match Esp32WifiNetwork::new() {
+        Ok(wifi) => return wifi,
+        Err(e) => log::warn!(
+            "failed to connect to the seeded default network ({:?}), scanning for any known network instead",
+            e
+        ),
+    }
+
+    let mut candidates = wifi_provisioning::strongest_known_networks(known).unwrap_or_default();
+    if candidates.is_empty() {
+        // The scan itself may have failed (radio not actually up yet) or
+        // just not seen any known network in range; either way, trying
+        // every known network in storage order beats giving up on the one
+        // blind guess already seeded.
+        candidates = known.to_vec();
+    }
+
+    for candidate in &candidates {
+        storage
+            .store_default_network(&candidate.ssid, &candidate.password)
+            .expect("failed to store network settings to storage");
+        match Esp32WifiNetwork::new() {
+            Ok(wifi) => return wifi,
+            Err(e) => log::warn!("failed to connect to '{}': {:?}", candidate.ssid, e),
+        }
+    }
+
+    Esp32WifiNetwork::new().expect("failed to connect to any known wifi network")
+}
+
 fn main() {
     esp_idf_svc::sys::link_patches();
     initialize_logger::<EspLogger>();
@@ -60,18 +116,89 @@ fn main() {
     // At runtime, if the program does not detect credentials or configs in storage,
     // it will try to load statically compiled values.
 
+    let nvs_part = esp_idf_svc::nvs::EspDefaultNvsPartition::take().unwrap();
+    let mut known_networks = wifi_provisioning::KnownNetworksStore::new(nvs_part.clone())
+        .expect("failed to open wifi_nets nvs");
+
+    // check if any were statically compiled
+    if let (Some(ssid), Some(pass)) = (SSID, PASS) {
+        let mut network = wifi_provisioning::KnownNetwork::new(ssid, pass)
+            .with_hidden(WIFI_HIDDEN == Some("true"));
+        if let Some(auth) = WIFI_AUTH.and_then(wifi_provisioning::AuthMode::parse) {
+            network = network.with_auth(auth);
+        }
+        known_networks
+            .remember(network)
+            .expect("failed to remember static network settings");
+    }
+
+    let mut mqtt_config_store =
+        telemetry::MqttConfigStore::new(nvs_part.clone()).expect("failed to open mqtt nvs");
+    if mqtt_config_store.load().is_none() {
+        if let Some(broker_url) = MQTT_BROKER_URL {
+            log::info!("storing static values from build time mqtt settings to storage");
+            mqtt_config_store
+                .store(&telemetry::MqttTelemetryConfig {
+                    broker_url: broker_url.to_owned(),
+                    topic_prefix: MQTT_TOPIC_PREFIX.unwrap_or("micro-rdk").to_owned(),
+                    interval_secs: 60,
+                })
+                .expect("failed to store mqtt settings to storage");
+        }
+    }
+    let mqtt_telemetry_config = mqtt_config_store.load();
+
+    // `esp_wifi_scan_start` requires the radio to already be initialized and
+    // started, so something has to be stored as the default network (and
+    // `Esp32WifiNetwork::new()` run) before `strongest_known_networks` can
+    // scan at all. Seed storage from the first known network if nothing is
+    // there yet; once the radio comes up we can scan and, if a stronger
+    // candidate is in range, switch to it in place below.
     if !storage.has_default_network() {
-        log::warn!("no default network settings found in storage");
+        if let Some(first) = known_networks.load().first() {
+            storage
+                .store_default_network(&first.ssid, &first.password)
+                .expect("failed to store network settings to storage");
+        } else {
+            log::warn!("no default network settings found in storage");
+        }
+    }
+    let wifi = bring_up_wifi(&storage, &known_networks.load());
 
-        // check if any were statically compiled
-        if SSID.is_some() && PASS.is_some() {
+    let candidates = match wifi_provisioning::strongest_known_networks(&known_networks.load()) {
+        Ok(candidates) if !candidates.is_empty() => {
             log::info!(
-                "storing static values from build time network settings to storage as default"
+                "found {} known network(s) in range, strongest is '{}'",
+                candidates.len(),
+                candidates[0].ssid
             );
-            storage
-                .store_default_network(SSID.unwrap(), PASS.unwrap())
-                .expect("failed to store network settings to storage");
+            candidates
+        }
+        Ok(_) => {
+            log::warn!("none of the known networks were seen during boot scan");
+            Vec::new()
         }
+        Err(e) => {
+            log::warn!(
+                "boot-time wifi scan failed, staying on the network already connected to: {:?}",
+                e
+            );
+            Vec::new()
+        }
+    };
+
+    // Reconfigures and reconnects the same already-initialized driver in
+    // place rather than constructing another `Esp32WifiNetwork`. Each
+    // candidate's stored auth mode is passed into the connection config
+    // explicitly rather than inferred, which hidden networks may require.
+    let current_ssid = wifi_provisioning::current_ssid().unwrap_or_default();
+    if let Some(winner) =
+        wifi_provisioning::switch_to_strongest(&candidates, &current_ssid, Duration::from_secs(15))
+    {
+        storage
+            .store_default_network(&winner.ssid, &winner.password)
+            .expect("failed to store network settings to storage");
+        log::info!("switched to stronger network '{}'", winner.ssid);
     }
 
     if !storage.has_robot_credentials() {
@@ -95,6 +222,45 @@ fn main() {
         }
     }
 
+    // Any build-time credentials were stored above (a failed store panics).
+    // Whether credentials are present is captured once here for the health
+    // gate; if none were found, the gate never passes, and credentials
+    // provisioned later at runtime are not seen by it.
+    let credentials_loaded = storage.has_robot_credentials();
+    let mqtt_configured = mqtt_telemetry_config.is_some();
+    let mqtt_connected = Arc::new(AtomicBool::new(false));
+    let ota_mqtt_connected = mqtt_connected.clone();
+    let ota_health_gate = ota::OtaHealthGate::new(
+        Duration::from_secs(
+            OTA_HEALTH_TIMEOUT_SECS
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(120),
+        ),
+        OTA_MIN_HEAP_PCT
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(20.0),
+    );
+    std::thread::spawn(move || {
+        // TODO(RSDK-9203): criterion (b), "established the Viam HTTP2/WebRTC
+        // session at least once", would ideally hook into
+        // `ViamServerBuilder`/the running server directly, but no such
+        // connection-established signal is exposed yet. In the meantime,
+        // a DHCP-assigned IP address (not just a WiFi association, which
+        // can exist without one) plus, when configured, a live MQTT
+        // `Connected` event that's actually tracked as such rather than any
+        // event at all - is real, continuously re-checked proof the device
+        // can reach the network. This is re-evaluated on every poll, so the
+        // gate can't mark an image valid before that's true.
+        let is_healthy = move || {
+            credentials_loaded
+                && wifi_provisioning::has_ip_address()
+                && (!mqtt_configured || ota_mqtt_connected.load(Ordering::Relaxed))
+        };
+        if let Err(e) = ota_health_gate.run(is_healthy) {
+            log::error!("ota health gate failed: {:?}", e);
+        }
+    });
+
     let mut info = ProvisioningInfo::default();
     info.set_manufacturer("viam".to_owned());
     info.set_model("esp32".to_owned());
@@ -107,15 +273,54 @@ fn main() {
         .build()
         .unwrap();
     let webrtc_certs = Rc::new(Box::new(webrtc_certs) as Box<dyn Certificate>);
+    // MQTT TLS (when the broker is `mqtts://`) reuses this same identity
+    // rather than asking for a second, separately-provisioned certificate.
+    let mqtt_tls_identity = webrtc_certs.clone();
     let dtls = Box::new(Esp32DtlsBuilder::new(webrtc_certs.clone()));
     let webrtc_config = WebRtcConfiguration::new(webrtc_certs, dtls);
 
     let exec = Executor::new();
 
-    exec.spawn(async {
+    let mut mqtt = mqtt_telemetry_config.as_ref().map(|config| {
+        let (client, mut connection) = telemetry::build_client(
+            config,
+            ROBOT_ID.unwrap_or("unknown"),
+            mqtt_tls_identity.as_ref().as_ref(),
+        )
+        .expect("failed to connect to mqtt broker");
+        // The connection has to be polled from somewhere for the client to
+        // make progress; give it its own thread rather than the async
+        // executor so a slow broker can't stall the heap-monitoring loop.
+        //
+        // `next()` returns `Ok(_)` for every event type, not just
+        // `Connected` - `BeforeConnect`/`Published`/`Error(_)` etc. all come
+        // back as `Ok`, so `.is_ok()` alone can't tell a live broker
+        // connection from an event loop that's merely running. Only
+        // `Connected` is treated as proof the OTA health gate can use, and
+        // `Disconnected` clears it back out so a dropped broker connection
+        // doesn't keep reporting stale health.
+        std::thread::spawn(move || {
+            use micro_rdk::esp32::esp_idf_svc::mqtt::client::EventPayload;
+            while let Ok(event) = connection.next() {
+                match event.payload() {
+                    EventPayload::Connected(_) => mqtt_connected.store(true, Ordering::Relaxed),
+                    EventPayload::Disconnected => mqtt_connected.store(false, Ordering::Relaxed),
+                    _ => {}
+                }
+            }
+        });
+        (client, telemetry::heap_topic(config, ROBOT_ID.unwrap_or("unknown")))
+    });
+    let heap_interval = mqtt_telemetry_config
+        .as_ref()
+        .map(|c| Duration::from_secs(c.interval_secs as u64))
+        .unwrap_or(Duration::from_secs(60));
+
+    exec.spawn(async move {
         loop {
             micro_rdk::esp32::utils::esp32_print_heap_summary!();
             log::info!(" Memory Status ");
+            use micro_rdk::esp32::esp_idf_svc::mqtt::client::QoS;
             use micro_rdk::esp32::esp_idf_svc::sys::{
                 heap_caps_get_free_size, heap_caps_get_total_size, uxTaskGetStackHighWaterMark,
                 MALLOC_CAP_8BIT, MALLOC_CAP_INTERNAL, MALLOC_CAP_SPIRAM,
@@ -140,10 +345,25 @@ fn main() {
                 total_ram,
                 ((total_ram_free as f32) / (total_ram as f32)) * 100.0
             );
-            log::info!("stack high watermark is {:#X}", unsafe {
-                uxTaskGetStackHighWaterMark(std::ptr::null_mut())
-            });
-            let _ = Timer::after(Duration::from_secs(60)).await;
+            let watermark =
+                unsafe { uxTaskGetStackHighWaterMark(std::ptr::null_mut()) };
+            log::info!("stack high watermark is {:#X}", watermark);
+
+            if let Some((client, topic)) = mqtt.as_mut() {
+                let payload = telemetry::heap_status_payload(
+                    total_spi_free,
+                    total_spi,
+                    total_ram_free,
+                    total_ram,
+                    watermark,
+                );
+                if let Err(e) = client.enqueue(topic, QoS::AtLeastOnce, false, payload.as_bytes())
+                {
+                    log::warn!("failed to publish heap telemetry: {:?}", e);
+                }
+            }
+
+            let _ = Timer::after(heap_interval).await;
         }
     })
     .detach();
@@ -156,7 +376,15 @@ fn main() {
         .with_default_tasks()
         .with_component_registry(registry);
 
-    let builder = { builder.with_wifi_manager(Box::new(Esp32WifiNetwork::new().unwrap())) };
+    // TODO(RSDK-9201): `with_wifi_manager` only accepts a `Box<Esp32WifiNetwork>`
+    // today, so WiFi is the only uplink this binary can actually select.
+    // Wired SPI Ethernet and cellular PPP backends were previously added
+    // here, but with no `with_network_manager(Box<dyn Network>)` entry point
+    // to hand either to, neither could ever be constructed from `main` -
+    // that's unreachable code, not a delivered feature, so both were pulled
+    // rather than left in the tree. Re-add the one that's needed once the
+    // builder is generic over a transport trait instead of one per manager.
+    let builder = { builder.with_wifi_manager(Box::new(wifi)) };
     let mdns = Esp32Mdns::new("".to_owned()).unwrap();
 
     let mut server = { builder.build(Esp32H2Connector::default(), exec, mdns) };
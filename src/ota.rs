@@ -0,0 +1,96 @@
+//! Confirms a freshly-flashed OTA image is healthy before it becomes
+//! permanent, rolling back to the previous partition otherwise.
+//!
+//! ESP-IDF's OTA partitions start in the "pending verify" state after a
+//! flash and expect something to call `esp_ota_mark_app_valid_cancel_rollback`
+//! before the next reboot, or the bootloader reverts to the previous slot
+//! automatically. This gate is that "something": it only flips the
+//! partition to permanent once the new image has proven it can actually
+//! store/load credentials and stays above a heap-free threshold for a
+//! sustained window.
+
+use std::time::{Duration, Instant};
+
+use micro_rdk::esp32::esp_idf_svc::ota::{EspOta, SlotState};
+use micro_rdk::esp32::esp_idf_svc::sys::{
+    heap_caps_get_free_size, heap_caps_get_total_size, EspError, MALLOC_CAP_8BIT,
+    MALLOC_CAP_INTERNAL,
+};
+
+/// How long free internal heap must stay above `min_heap_pct` before the
+/// image is considered healthy.
+const SUSTAIN_WINDOW: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct OtaHealthGate {
+    timeout: Duration,
+    min_heap_pct: f32,
+}
+
+impl OtaHealthGate {
+    pub fn new(timeout: Duration, min_heap_pct: f32) -> Self {
+        Self {
+            timeout,
+            min_heap_pct,
+        }
+    }
+
+    /// No-op if the running partition isn't in the pending-verify state
+    /// (e.g. a normal boot, not one right after an OTA update). Otherwise
+    /// blocks for up to `timeout`, checking `is_healthy` and the heap
+    /// threshold once per second, and either marks the app valid or rolls
+    /// back and reboots.
+    ///
+    /// `is_healthy` is re-evaluated on every poll rather than captured once,
+    /// so it must itself report live state (e.g. "is the WiFi driver
+    /// currently associated", not "were credentials loaded at boot") -
+    /// callers should fold network reachability into it, since a heap
+    /// check alone would happily mark a dead-on-arrival image valid.
+    pub fn run(&self, is_healthy: impl Fn() -> bool) -> Result<(), EspError> {
+        let mut ota = EspOta::new()?;
+        if ota.get_running_slot()?.state != SlotState::Pending {
+            return Ok(());
+        }
+
+        log::info!(
+            "running partition is pending verification, starting ota health gate (timeout {:?}, min free heap {:.1}%)",
+            self.timeout,
+            self.min_heap_pct
+        );
+
+        let deadline = Instant::now() + self.timeout;
+        let mut healthy_since: Option<Instant> = None;
+
+        loop {
+            let healthy_now = is_healthy() && internal_heap_free_pct() >= self.min_heap_pct;
+            healthy_since = match (healthy_since, healthy_now) {
+                (Some(since), true) => Some(since),
+                (_, true) => Some(Instant::now()),
+                (_, false) => None,
+            };
+
+            if let Some(since) = healthy_since {
+                if since.elapsed() >= SUSTAIN_WINDOW {
+                    log::info!("ota health gate passed, marking running partition valid");
+                    ota.mark_running_slot_valid()?;
+                    return Ok(());
+                }
+            }
+
+            if Instant::now() >= deadline {
+                log::error!("ota health gate timed out, rolling back to previous partition");
+                ota.mark_running_slot_invalid_and_reboot();
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn internal_heap_free_pct() -> f32 {
+    unsafe {
+        let total = heap_caps_get_total_size(MALLOC_CAP_INTERNAL | MALLOC_CAP_8BIT) as f32;
+        let free = heap_caps_get_free_size(MALLOC_CAP_INTERNAL | MALLOC_CAP_8BIT) as f32;
+        (free / total) * 100.0
+    }
+}
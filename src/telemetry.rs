@@ -0,0 +1,152 @@
+//! Publishes the numbers already gathered by the heap-monitoring task to an
+//! MQTT broker so they're visible somewhere other than the serial console.
+
+use micro_rdk::common::webrtc::certificate::Certificate;
+use micro_rdk::esp32::esp_idf_svc::mqtt::client::{
+    EspMqttClient, EspMqttConnection, LwtConfiguration, MqttClientConfiguration, QoS,
+};
+use micro_rdk::esp32::esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use micro_rdk::esp32::esp_idf_svc::sys::EspError;
+use micro_rdk::esp32::esp_idf_svc::tls::X509;
+
+const NVS_NAMESPACE: &str = "mqtt";
+const BROKER_KEY: &str = "broker";
+const PREFIX_KEY: &str = "prefix";
+const INTERVAL_KEY: &str = "interval_s";
+
+#[derive(Debug, Clone)]
+pub struct MqttTelemetryConfig {
+    pub broker_url: String,
+    pub topic_prefix: String,
+    pub interval_secs: u32,
+}
+
+pub struct MqttConfigStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl MqttConfigStore {
+    pub fn new(part: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(part, NVS_NAMESPACE, true)?,
+        })
+    }
+
+    pub fn load(&self) -> Option<MqttTelemetryConfig> {
+        let mut broker_buf = [0u8; 256];
+        let broker_url = self
+            .nvs
+            .get_str(BROKER_KEY, &mut broker_buf)
+            .ok()
+            .flatten()?
+            .to_owned();
+        let mut prefix_buf = [0u8; 128];
+        let topic_prefix = self
+            .nvs
+            .get_str(PREFIX_KEY, &mut prefix_buf)
+            .ok()
+            .flatten()
+            .unwrap_or("micro-rdk")
+            .to_owned();
+        let interval_secs = self.nvs.get_u32(INTERVAL_KEY).ok().flatten().unwrap_or(60);
+        Some(MqttTelemetryConfig {
+            broker_url,
+            topic_prefix,
+            interval_secs,
+        })
+    }
+
+    pub fn store(&mut self, config: &MqttTelemetryConfig) -> Result<(), EspError> {
+        self.nvs.set_str(BROKER_KEY, &config.broker_url)?;
+        self.nvs.set_str(PREFIX_KEY, &config.topic_prefix)?;
+        self.nvs.set_u32(INTERVAL_KEY, config.interval_secs)?;
+        Ok(())
+    }
+}
+
+/// JSON-encodes per-capability heap usage and the task stack watermark.
+pub fn heap_status_payload(
+    spirsm_free: u32,
+    spirsm_total: u32,
+    internal_free: u32,
+    internal_total: u32,
+    stack_watermark: u32,
+) -> String {
+    format!(
+        "{{\"spiram_free\":{spirsm_free},\"spiram_total\":{spirsm_total},\
+         \"internal_free\":{internal_free},\"internal_total\":{internal_total},\
+         \"stack_watermark\":{stack_watermark}}}"
+    )
+}
+
+/// Builds an MQTT client connected to `config.broker_url`, announcing an
+/// offline LWT on `<topic_prefix>/<robot_id>/status` with QoS 1 so a dropped device is
+/// visible to dashboards without waiting on a keepalive timeout upstream.
+///
+/// When `config.broker_url` is an `mqtts://` endpoint, `identity` is used as
+/// the client certificate/key so the broker can authenticate the device the
+/// same way a WebRTC peer would - it's the same `Certificate` implementation
+/// `main` already builds for DTLS, reused here rather than asking for a
+/// second, separately-provisioned cert.
+pub fn build_client(
+    config: &MqttTelemetryConfig,
+    robot_id: &str,
+    identity: &dyn Certificate,
+) -> Result<(EspMqttClient<'static>, EspMqttConnection), EspError> {
+    let status_topic = format!("{}/{}/status", config.topic_prefix, robot_id);
+    let is_tls = config.broker_url.starts_with("mqtts://");
+
+    let der_cert;
+    let der_key;
+    let (client_certificate, private_key) = if is_tls {
+        der_cert = identity.get_certificate_der();
+        der_key = identity.get_private_key_der();
+        (Some(X509::der(&der_cert)), Some(X509::der(&der_key)))
+    } else {
+        (None, None)
+    };
+
+    let mqtt_config = MqttClientConfiguration {
+        lwt: Some(LwtConfiguration {
+            topic: &status_topic,
+            payload: b"offline",
+            qos: QoS::AtLeastOnce,
+            retain: true,
+        }),
+        client_certificate,
+        private_key,
+        ..Default::default()
+    };
+    EspMqttClient::new(&config.broker_url, &mqtt_config)
+}
+
+/// Topic readings are published to: `<topic_prefix>/<robot_id>/heap`.
+pub fn heap_topic(config: &MqttTelemetryConfig, robot_id: &str) -> String {
+    format!("{}/{}/heap", config.topic_prefix, robot_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_status_payload_is_well_formed_json() {
+        let payload = heap_status_payload(1000, 2000, 3000, 4000, 512);
+        assert_eq!(
+            payload,
+            "{\"spiram_free\":1000,\"spiram_total\":2000,\
+             \"internal_free\":3000,\"internal_total\":4000,\
+             \"stack_watermark\":512}"
+        );
+    }
+
+    #[test]
+    fn heap_topic_includes_robot_id() {
+        let config = MqttTelemetryConfig {
+            broker_url: "mqtt://localhost".to_owned(),
+            topic_prefix: "micro-rdk".to_owned(),
+            interval_secs: 60,
+        };
+        assert_eq!(heap_topic(&config, "robot-1"), "micro-rdk/robot-1/heap");
+    }
+}
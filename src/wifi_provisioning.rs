@@ -0,0 +1,555 @@
+//! Scan-and-select helpers for connecting to the strongest of several
+//! provisioned WiFi networks.
+//!
+//! `micro_rdk`'s `WifiCredentialStorage` only keeps a single "default"
+//! network, and `Esp32WifiNetwork::new()` connects to whatever is stored
+//! there blindly. Until that storage trait grows first-class support for a
+//! list of networks, we keep our own small list alongside it in NVS and, on
+//! boot, run an active scan to figure out which of the known networks are
+//! actually in range before picking one and handing it to
+//! `store_default_network` so the rest of the stack is untouched.
+
+use std::ffi::CStr;
+
+use micro_rdk::esp32::esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use micro_rdk::esp32::esp_idf_svc::sys::{
+    esp, wifi_ap_record_t, wifi_auth_mode_t_WIFI_AUTH_OPEN as WIFI_AUTH_OPEN,
+    wifi_auth_mode_t_WIFI_AUTH_WAPI_PSK as WIFI_AUTH_WAPI_PSK,
+    wifi_auth_mode_t_WIFI_AUTH_WEP as WIFI_AUTH_WEP,
+    wifi_auth_mode_t_WIFI_AUTH_WPA2_ENTERPRISE as WIFI_AUTH_WPA2_ENTERPRISE,
+    wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK as WIFI_AUTH_WPA2_PSK,
+    wifi_auth_mode_t_WIFI_AUTH_WPA3_PSK as WIFI_AUTH_WPA3_PSK,
+    wifi_auth_mode_t_WIFI_AUTH_WPA_PSK as WIFI_AUTH_WPA_PSK, EspError,
+};
+
+const NVS_NAMESPACE: &str = "wifi_nets";
+const NVS_KEY: &str = "known";
+const MAX_KNOWN_NETWORKS: usize = 8;
+
+/// Authentication scheme of a scanned or provisioned access point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    Open,
+    Wep,
+    WpaPsk,
+    Wpa2Psk,
+    Wpa3Psk,
+    Wpa2Enterprise,
+    Other,
+}
+
+impl From<u32> for AuthMode {
+    fn from(authmode: u32) -> Self {
+        match authmode {
+            WIFI_AUTH_OPEN => AuthMode::Open,
+            WIFI_AUTH_WEP => AuthMode::Wep,
+            WIFI_AUTH_WPA_PSK => AuthMode::WpaPsk,
+            WIFI_AUTH_WPA2_PSK => AuthMode::Wpa2Psk,
+            WIFI_AUTH_WPA3_PSK => AuthMode::Wpa3Psk,
+            WIFI_AUTH_WPA2_ENTERPRISE => AuthMode::Wpa2Enterprise,
+            WIFI_AUTH_WAPI_PSK => AuthMode::Other,
+            _ => AuthMode::Other,
+        }
+    }
+}
+
+impl AuthMode {
+    /// Single-char tag used by the NVS encoding below.
+    fn tag(self) -> u8 {
+        match self {
+            AuthMode::Open => b'o',
+            AuthMode::Wep => b'w',
+            AuthMode::WpaPsk => b'1',
+            AuthMode::Wpa2Psk => b'2',
+            AuthMode::Wpa3Psk => b'3',
+            AuthMode::Wpa2Enterprise => b'e',
+            AuthMode::Other => b'?',
+        }
+    }
+
+    fn from_tag(tag: u8) -> Self {
+        match tag {
+            b'o' => AuthMode::Open,
+            b'w' => AuthMode::Wep,
+            b'1' => AuthMode::WpaPsk,
+            b'2' => AuthMode::Wpa2Psk,
+            b'3' => AuthMode::Wpa3Psk,
+            b'e' => AuthMode::Wpa2Enterprise,
+            _ => AuthMode::Other,
+        }
+    }
+
+    /// Parses the `MICRO_RDK_WIFI_AUTH` build-time constant. `Wpa2Enterprise`
+    /// still parses (and round-trips through NVS) so a network recorded with
+    /// that auth mode isn't silently corrupted, but see `is_connectable` -
+    /// nothing in this module will actually attempt to connect to one.
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "none" | "open" => AuthMode::Open,
+            "wep" => AuthMode::Wep,
+            "wpa" => AuthMode::WpaPsk,
+            "wpa2" => AuthMode::Wpa2Psk,
+            "wpa3" => AuthMode::Wpa3Psk,
+            "wpa2-enterprise" | "wpa2_enterprise" => AuthMode::Wpa2Enterprise,
+            _ => return None,
+        })
+    }
+
+    /// The `wifi_auth_mode_t` value to put in `wifi_sta_config_t.threshold`,
+    /// the inverse of `From<u32>`. `Other` falls back to `WPA2_PSK`, the same
+    /// assumption the rest of the stack made before this type existed.
+    ///
+    /// Note that `threshold.authmode` is a *minimum acceptable security
+    /// level* the driver will accept when picking an AP, not a selector for
+    /// which authentication method to actually perform - pairing it with
+    /// `config.sta.password` is enough for PSK modes (and WEP, which just
+    /// uses the password field as the WEP key), but it does nothing to set
+    /// up WPA2-Enterprise's EAP identity/CA certificate, which is a wholly
+    /// separate `esp_wifi_sta_enterprise_*` API this module doesn't touch.
+    /// That's why `is_connectable` excludes `Wpa2Enterprise` rather than
+    /// pretending this config is sufficient for it.
+    fn to_sys(self) -> u32 {
+        match self {
+            AuthMode::Open => WIFI_AUTH_OPEN,
+            AuthMode::Wep => WIFI_AUTH_WEP,
+            AuthMode::WpaPsk => WIFI_AUTH_WPA_PSK,
+            AuthMode::Wpa2Psk => WIFI_AUTH_WPA2_PSK,
+            AuthMode::Wpa3Psk => WIFI_AUTH_WPA3_PSK,
+            AuthMode::Wpa2Enterprise => WIFI_AUTH_WPA2_ENTERPRISE,
+            AuthMode::Other => WIFI_AUTH_WPA2_PSK,
+        }
+    }
+
+    /// Whether this module can actually connect to a network using this
+    /// auth mode. Every variant except `Wpa2Enterprise` only needs a
+    /// password plus `to_sys()` in the connection config; EAP-based
+    /// enterprise auth needs identity/CA certificate setup via
+    /// `esp_wifi_sta_enterprise_*` that nothing here implements, so rather
+    /// than silently failing to associate, such networks are treated as not
+    /// connectable and skipped before an attempt is ever made.
+    fn is_connectable(self) -> bool {
+        !matches!(self, AuthMode::Wpa2Enterprise)
+    }
+}
+
+/// A network the user has provisioned, statically or at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownNetwork {
+    pub ssid: String,
+    pub password: String,
+    pub auth: AuthMode,
+    /// Hidden SSIDs don't show up in a passive/normal scan, so they need an
+    /// active probe naming the SSID explicitly.
+    pub hidden: bool,
+}
+
+impl KnownNetwork {
+    pub fn new(ssid: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            ssid: ssid.into(),
+            password: password.into(),
+            auth: AuthMode::Wpa2Psk,
+            hidden: false,
+        }
+    }
+
+    pub fn with_auth(mut self, auth: AuthMode) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+}
+
+/// A network actually seen over the air during a scan.
+#[derive(Debug, Clone)]
+pub struct ScannedNetwork {
+    pub ssid: String,
+    pub rssi: i8,
+    pub auth: AuthMode,
+}
+
+/// Persists the list of known (ssid, password) pairs in their own NVS
+/// namespace, separate from `NVSStorage`'s single default-network slot.
+pub struct KnownNetworksStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl KnownNetworksStore {
+    pub fn new(part: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(part, NVS_NAMESPACE, true)?,
+        })
+    }
+
+    /// Loads the known network list, or an empty list if nothing has been
+    /// stored yet.
+    pub fn load(&self) -> Vec<KnownNetwork> {
+        let mut buf = [0u8; 1024];
+        match self.nvs.get_raw(NVS_KEY, &mut buf) {
+            Ok(Some(bytes)) => decode(bytes),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn store(&mut self, networks: &[KnownNetwork]) -> Result<(), EspError> {
+        let encoded = encode(networks);
+        self.nvs.set_raw(NVS_KEY, &encoded)?;
+        Ok(())
+    }
+
+    /// Adds `network` to the stored list if it isn't already present,
+    /// keeping at most `MAX_KNOWN_NETWORKS` entries.
+    pub fn remember(&mut self, network: KnownNetwork) -> Result<(), EspError> {
+        let mut networks = self.load();
+        if !networks.iter().any(|n| n.ssid == network.ssid) {
+            if networks.len() >= MAX_KNOWN_NETWORKS {
+                networks.remove(0);
+            }
+            networks.push(network);
+            self.store(&networks)?;
+        }
+        Ok(())
+    }
+}
+
+/// `ssid_len:ssid;password_len:password;auth_tag;hidden_flag;` repeated per
+/// network, so neither ssid nor password needs escaping.
+fn encode(networks: &[KnownNetwork]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for n in networks {
+        out.extend_from_slice(format!("{}:", n.ssid.len()).as_bytes());
+        out.extend_from_slice(n.ssid.as_bytes());
+        out.extend_from_slice(format!(";{}:", n.password.len()).as_bytes());
+        out.extend_from_slice(n.password.as_bytes());
+        out.push(b';');
+        out.push(n.auth.tag());
+        out.push(b';');
+        out.push(if n.hidden { b'1' } else { b'0' });
+        out.push(b';');
+    }
+    out
+}
+
+fn decode(bytes: &[u8]) -> Vec<KnownNetwork> {
+    let mut networks = Vec::new();
+    let mut rest = bytes;
+    while let Some((ssid, after)) = take_field(rest) {
+        let Some((password, after)) = take_field(after) else {
+            break;
+        };
+        let (auth, after) = match after.split_first() {
+            Some((tag, after)) => (AuthMode::from_tag(*tag), after.get(1..).unwrap_or(&[])),
+            None => (AuthMode::Wpa2Psk, after),
+        };
+        let (hidden, after) = match after.split_first() {
+            Some((flag, after)) => (*flag == b'1', after.get(1..).unwrap_or(&[])),
+            None => (false, after),
+        };
+        networks.push(
+            KnownNetwork::new(ssid, password)
+                .with_auth(auth)
+                .with_hidden(hidden),
+        );
+        rest = after;
+    }
+    networks
+}
+
+fn take_field(bytes: &[u8]) -> Option<(String, &[u8])> {
+    let colon = bytes.iter().position(|b| *b == b':')?;
+    let len: usize = std::str::from_utf8(&bytes[..colon]).ok()?.parse().ok()?;
+    let start = colon + 1;
+    let end = start + len;
+    let field = std::str::from_utf8(bytes.get(start..end)?).ok()?.to_owned();
+    let rest = bytes.get(end + 1..).unwrap_or(&[]);
+    Some((field, rest))
+}
+
+/// Runs a blocking active scan and returns every AP the radio saw.
+pub fn scan() -> Result<Vec<ScannedNetwork>, EspError> {
+    use micro_rdk::esp32::esp_idf_svc::sys::{
+        esp_wifi_scan_get_ap_num, esp_wifi_scan_get_ap_records, esp_wifi_scan_start,
+    };
+
+    unsafe {
+        esp!(esp_wifi_scan_start(std::ptr::null(), true))?;
+    }
+
+    let mut num: u16 = 0;
+    unsafe {
+        esp!(esp_wifi_scan_get_ap_num(&mut num))?;
+    }
+
+    let mut records: Vec<wifi_ap_record_t> = vec![unsafe { std::mem::zeroed() }; num as usize];
+    let mut actual = num;
+    unsafe {
+        esp!(esp_wifi_scan_get_ap_records(&mut actual, records.as_mut_ptr()))?;
+    }
+    records.truncate(actual as usize);
+
+    Ok(records.iter().filter_map(record_to_network).collect())
+}
+
+fn ssid_from_record(ssid: &[u8]) -> Option<String> {
+    let nul = ssid.iter().position(|b| *b == 0).unwrap_or(ssid.len());
+    let ssid_bytes: Vec<u8> = ssid[..nul].iter().map(|b| *b as u8).collect();
+    let ssid = CStr::from_bytes_with_nul(&[ssid_bytes.as_slice(), &[0]].concat())
+        .ok()?
+        .to_str()
+        .ok()?
+        .to_owned();
+    if ssid.is_empty() {
+        None
+    } else {
+        Some(ssid)
+    }
+}
+
+fn record_to_network(record: &wifi_ap_record_t) -> Option<ScannedNetwork> {
+    Some(ScannedNetwork {
+        ssid: ssid_from_record(&record.ssid)?,
+        rssi: record.rssi,
+        auth: AuthMode::from(record.authmode),
+    })
+}
+
+/// The SSID the STA driver is currently associated with, if any. Used to
+/// avoid needlessly reconnecting to the network already in use.
+pub fn current_ssid() -> Option<String> {
+    use micro_rdk::esp32::esp_idf_svc::sys::esp_wifi_sta_get_ap_info;
+
+    let mut info: wifi_ap_record_t = unsafe { std::mem::zeroed() };
+    if unsafe { esp_wifi_sta_get_ap_info(&mut info) } != 0 {
+        return None;
+    }
+    ssid_from_record(&info.ssid)
+}
+
+/// Whether the STA interface currently has a DHCP-assigned IPv4 address, not
+/// just an association - a WiFi association can briefly exist without a
+/// usable IP (DHCP still in flight, or a renewal that failed), so callers
+/// that need proof the device can actually pass traffic, like the OTA health
+/// gate, check this instead of `current_ssid` alone.
+pub fn has_ip_address() -> bool {
+    use micro_rdk::esp32::esp_idf_svc::sys::{
+        esp_netif_get_handle_from_ifkey, esp_netif_get_ip_info, esp_netif_ip_info_t,
+    };
+
+    let ifkey = std::ffi::CString::new("WIFI_STA_DEF").expect("static string has no interior nul");
+    let handle = unsafe { esp_netif_get_handle_from_ifkey(ifkey.as_ptr()) };
+    if handle.is_null() {
+        return false;
+    }
+    let mut info: esp_netif_ip_info_t = unsafe { std::mem::zeroed() };
+    if unsafe { esp_netif_get_ip_info(handle, &mut info) } != 0 {
+        return false;
+    }
+    info.ip.addr != 0
+}
+
+/// Actively probes for a specific (potentially hidden) SSID, since hidden
+/// networks don't appear in a normal/passive scan's results.
+pub fn probe_for_ssid(ssid: &str) -> Result<bool, EspError> {
+    use micro_rdk::esp32::esp_idf_svc::sys::{
+        esp_wifi_scan_get_ap_num, esp_wifi_scan_start, wifi_scan_config_t,
+        wifi_scan_type_t_WIFI_SCAN_TYPE_ACTIVE as WIFI_SCAN_TYPE_ACTIVE,
+    };
+
+    let mut ssid_bytes: Vec<u8> = ssid.bytes().chain(std::iter::once(0)).collect();
+    let config = wifi_scan_config_t {
+        ssid: ssid_bytes.as_mut_ptr(),
+        bssid: std::ptr::null_mut(),
+        channel: 0,
+        show_hidden: true,
+        scan_type: WIFI_SCAN_TYPE_ACTIVE,
+        scan_time: unsafe { std::mem::zeroed() },
+    };
+
+    unsafe {
+        esp!(esp_wifi_scan_start(&config, true))?;
+    }
+    let mut num: u16 = 0;
+    unsafe {
+        esp!(esp_wifi_scan_get_ap_num(&mut num))?;
+    }
+    Ok(num > 0)
+}
+
+/// Scans for nearby networks, keeps only the ones that are also in `known`
+/// and that we can actually connect to (see `AuthMode::is_connectable`), and
+/// returns them ordered from strongest to weakest signal. Hidden networks
+/// can't be matched against a normal scan (no SSID is broadcast), so each
+/// one is probed for explicitly instead and, if present, appended after the
+/// networks found by signal strength.
+pub fn strongest_known_networks(known: &[KnownNetwork]) -> Result<Vec<KnownNetwork>, EspError> {
+    let mut seen = scan()?;
+    seen.sort_by_key(|s| std::cmp::Reverse(s.rssi));
+
+    let mut candidates = Vec::new();
+    for scanned in &seen {
+        if let Some(matching) = known
+            .iter()
+            .find(|k| !k.hidden && k.auth.is_connectable() && k.ssid == scanned.ssid)
+        {
+            candidates.push(matching.clone());
+        }
+    }
+
+    for hidden in known.iter().filter(|k| k.hidden && k.auth.is_connectable()) {
+        if probe_for_ssid(&hidden.ssid)? {
+            candidates.push(hidden.clone());
+        }
+    }
+
+    Ok(candidates)
+}
+
+fn copy_into(dst: &mut [u8], src: &str) {
+    let len = src.len().min(dst.len().saturating_sub(1));
+    dst[..len].copy_from_slice(&src.as_bytes()[..len]);
+}
+
+/// Tries each candidate in order against the *already-initialized* STA
+/// driver, strongest signal first, giving each `timeout` to associate
+/// before moving on to the next. This reconfigures and reconnects the same
+/// driver rather than tearing down and recreating `Esp32WifiNetwork`, which
+/// would re-run `esp_wifi_init`/`esp_wifi_start` on top of an already
+/// initialized radio.
+///
+/// `candidate.auth` is passed into the connection config explicitly (rather
+/// than left for the driver to infer), which hidden networks may need in
+/// order to associate at all. Setting an explicit `ssid` in the config also
+/// makes the STA send a directed probe request during association, which is
+/// what actually finds a hidden network - `hidden` only changes how
+/// `strongest_known_networks` detects presence during the boot scan above.
+///
+/// Candidates callers already filtered through `strongest_known_networks`
+/// won't include any that aren't `AuthMode::is_connectable`, but this skips
+/// them too in case a caller ever passes an unfiltered list directly.
+///
+/// Returns the candidate that associated successfully, or `None` if
+/// `current_ssid` is already the strongest one in range, or if none of
+/// them could be associated with.
+pub fn switch_to_strongest(
+    candidates: &[KnownNetwork],
+    current_ssid: &str,
+    timeout: std::time::Duration,
+) -> Option<KnownNetwork> {
+    use micro_rdk::esp32::esp_idf_svc::sys::{
+        esp_wifi_connect, esp_wifi_disconnect, esp_wifi_set_config, esp_wifi_sta_get_ap_info,
+        wifi_config_t, wifi_interface_t_WIFI_IF_STA as WIFI_IF_STA,
+    };
+    use std::time::Instant;
+
+    if candidates.first().map(|c| c.ssid.as_str()) == Some(current_ssid) {
+        return None;
+    }
+
+    for candidate in candidates {
+        if candidate.ssid == current_ssid || !candidate.auth.is_connectable() {
+            continue;
+        }
+
+        let mut config: wifi_config_t = unsafe { std::mem::zeroed() };
+        unsafe {
+            copy_into(&mut config.sta.ssid, &candidate.ssid);
+            copy_into(&mut config.sta.password, &candidate.password);
+            config.sta.threshold.authmode = candidate.auth.to_sys();
+        }
+
+        unsafe {
+            let _ = esp_wifi_disconnect();
+            if esp!(esp_wifi_set_config(WIFI_IF_STA, &mut config)).is_err() {
+                continue;
+            }
+            if esp!(esp_wifi_connect()).is_err() {
+                continue;
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut associated = false;
+        while Instant::now() < deadline {
+            let mut info = unsafe { std::mem::zeroed() };
+            if unsafe { esp_wifi_sta_get_ap_info(&mut info) } == 0 {
+                associated = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(200));
+        }
+
+        if associated {
+            return Some(candidate.clone());
+        }
+        log::warn!(
+            "failed to associate with '{}' within {:?}, trying next candidate",
+            candidate.ssid,
+            timeout
+        );
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_mode_tag_round_trips() {
+        for mode in [
+            AuthMode::Open,
+            AuthMode::Wep,
+            AuthMode::WpaPsk,
+            AuthMode::Wpa2Psk,
+            AuthMode::Wpa3Psk,
+            AuthMode::Wpa2Enterprise,
+        ] {
+            assert_eq!(AuthMode::from_tag(mode.tag()), mode);
+        }
+    }
+
+    #[test]
+    fn auth_mode_parse_recognizes_build_time_names() {
+        assert_eq!(AuthMode::parse("open"), Some(AuthMode::Open));
+        assert_eq!(AuthMode::parse("WPA2"), Some(AuthMode::Wpa2Psk));
+        assert_eq!(
+            AuthMode::parse("wpa2-enterprise"),
+            Some(AuthMode::Wpa2Enterprise)
+        );
+        assert_eq!(AuthMode::parse("not-a-real-mode"), None);
+    }
+
+    #[test]
+    fn wpa2_enterprise_is_not_connectable() {
+        assert!(!AuthMode::Wpa2Enterprise.is_connectable());
+        assert!(AuthMode::Wep.is_connectable());
+        assert!(AuthMode::Wpa2Psk.is_connectable());
+    }
+
+    #[test]
+    fn known_network_list_round_trips_through_encode_decode() {
+        let networks = vec![
+            KnownNetwork::new("first", "password-one"),
+            KnownNetwork::new("second", "")
+                .with_auth(AuthMode::Wep)
+                .with_hidden(true),
+            KnownNetwork::new("third", "p@ss;w:rd")
+                .with_auth(AuthMode::Wpa3Psk)
+                .with_hidden(false),
+        ];
+
+        let encoded = encode(&networks);
+        let decoded = decode(&encoded);
+
+        assert_eq!(decoded, networks);
+    }
+
+    #[test]
+    fn decode_of_empty_bytes_is_empty() {
+        assert!(decode(&[]).is_empty());
+    }
+}